@@ -8,6 +8,7 @@ impl<'de> Deserialize<'de> for Delimiter {
         let c = String::deserialize(d)?;
         match &*c {
             r"\t" => Ok(Delimiter(b'\t')),
+            r"\0" => Ok(Delimiter(b'\0')),
             s => {
                 if s.len() != 1 {
                     let msg = format!(
@@ -0,0 +1,138 @@
+use std::io;
+
+use encoding_rs::EncoderResult;
+use serde::de::{Deserialize, Deserializer, Error};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Encoding(pub &'static encoding_rs::Encoding);
+
+impl<'de> Deserialize<'de> for Encoding {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Encoding, D::Error> {
+        let label = String::deserialize(d)?;
+        match encoding_rs::Encoding::for_label(label.as_bytes()) {
+            Some(encoding) => Ok(Encoding(encoding)),
+            None => {
+                let msg = format!("Unknown character encoding '{}'.", label);
+                Err(D::Error::custom(msg))
+            }
+        }
+    }
+}
+
+/// Transcodes UTF-8 written to it into another character encoding.
+///
+/// Characters which have no representation in the target encoding are
+/// replaced with `?`, unless the writer is strict, in which case they
+/// produce an error.
+///
+/// Flushing the writer returns a stateful encoding, such as ISO-2022-JP, to
+/// its initial state, so the output is only complete once it is flushed.
+pub struct EncodingWriter<W: io::Write> {
+    inner: W,
+    encoder: encoding_rs::Encoder,
+    strict: bool,
+    // Trailing bytes of an incomplete UTF-8 sequence from the last write.
+    pending: Vec<u8>,
+    buf: Vec<u8>,
+}
+
+impl<W: io::Write> EncodingWriter<W> {
+    pub fn new(inner: W, encoding: &'static encoding_rs::Encoding, strict: bool) -> Self {
+        EncodingWriter {
+            inner,
+            encoder: encoding.new_encoder(),
+            strict,
+            pending: Vec::new(),
+            buf: vec![0; 4096],
+        }
+    }
+
+    // Encodes all of `src` before writing any of it, so that a failure
+    // doesn't leave a partial chunk in the underlying writer.
+    fn encode(&mut self, mut src: &str, last: bool) -> io::Result<()> {
+        let mut out = Vec::with_capacity(src.len());
+        loop {
+            let (result, read, written) =
+                self.encoder
+                    .encode_from_utf8_without_replacement(src, &mut self.buf, last);
+            out.extend_from_slice(&self.buf[..written]);
+            src = &src[read..];
+            match result {
+                EncoderResult::InputEmpty => break,
+                EncoderResult::OutputFull => continue,
+                EncoderResult::Unmappable(c) => {
+                    if self.strict {
+                        let msg = format!(
+                            "character '{}' can't be encoded as {}",
+                            c,
+                            self.encoder.encoding().name()
+                        );
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+                    }
+                    out.push(b'?');
+                }
+            }
+        }
+        self.inner.write_all(&out)
+    }
+}
+
+impl<W: io::Write> io::Write for EncodingWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut bytes = std::mem::take(&mut self.pending);
+        bytes.extend_from_slice(data);
+
+        let valid = match std::str::from_utf8(&bytes) {
+            Ok(_) => bytes.len(),
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+        };
+
+        // The prefix up to `valid` was checked above.
+        let text = std::str::from_utf8(&bytes[..valid]).unwrap();
+        self.encode(text, false)?;
+        self.pending = bytes[valid..].to_vec();
+        Ok(data.len())
+    }
+
+    // A flush between the bytes of one character leaves the encoder as it
+    // is, since the character can't be encoded until the rest of it arrives.
+    fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            self.encode("", true)?;
+            self.encoder = self.encoder.encoding().new_encoder();
+        }
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_encoding_writer_split_character() {
+        let mut out = Vec::new();
+        {
+            let mut wtr = EncodingWriter::new(&mut out, encoding_rs::WINDOWS_1252, true);
+            let bytes = "café".as_bytes();
+            wtr.write_all(&bytes[..4]).unwrap();
+            wtr.write_all(&bytes[4..]).unwrap();
+        }
+        assert_eq!(out, b"caf\xe9");
+    }
+
+    #[test]
+    fn test_encoding_writer_flush_resets_state() {
+        let mut out = Vec::new();
+        {
+            let mut wtr = EncodingWriter::new(&mut out, encoding_rs::ISO_2022_JP, true);
+            wtr.write_all("日本".as_bytes()).unwrap();
+            wtr.flush().unwrap();
+            wtr.write_all("語".as_bytes()).unwrap();
+            wtr.flush().unwrap();
+        }
+        assert_eq!(out, b"\x1b$BF|K\\\x1b(B\x1b$B8l\x1b(B");
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+// `href` and `src` attributes, with double, single or no quotes around the value.
+static LINK_PATTERN: &str = r#"(?i)\b(?:href|src)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'<>`=]+))"#;
+
+/// Find the URLs of links and embedded resources in a fragment of HTML.
+///
+/// This is a best-effort scan for `href` and `src` attributes rather than a
+/// full HTML parser: attributes inside comments or scripts are found too,
+/// and only the `&amp;` character reference is decoded.
+pub fn links(html: &str) -> Vec<String> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| Regex::new(LINK_PATTERN).unwrap());
+
+    pattern
+        .captures_iter(html)
+        .filter_map(|cap| cap.get(1).or_else(|| cap.get(2)).or_else(|| cap.get(3)))
+        .map(|value| value.as_str().trim().replace("&amp;", "&"))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_links() {
+        let html = r#"<a class="x" HREF="https://example.com/?a=1&amp;b=2">x</a>
+            <img src='/logo.png'><link href=style.css rel=stylesheet><p>href</p>"#;
+        assert_eq!(
+            links(html),
+            vec!["https://example.com/?a=1&b=2", "/logo.png", "style.css"]
+        );
+        assert!(links("no links here").is_empty());
+    }
+}
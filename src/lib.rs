@@ -0,0 +1,8 @@
+//! Split URLs into their component parts, as used by the `urlsplit` command.
+
+pub mod split;
+
+pub use split::{
+    column_type, header_record, parse_url, parts_from_url, split_log_line, split_url, ColumnType,
+    Options, SplitError, UrlParts,
+};
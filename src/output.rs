@@ -0,0 +1,1562 @@
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+
+use serde::de::{Deserialize, Deserializer, Error};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use urlsplit::split::ColumnType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Tsv,
+    Json,
+    Jsonl,
+    Markdown,
+    Table,
+    Avro,
+    MessagePack,
+    Sql,
+    PgCopy,
+}
+
+impl Format {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Tsv => "tsv",
+            Format::Json => "json",
+            Format::Jsonl => "jsonl",
+            Format::Markdown => "markdown",
+            Format::Table => "table",
+            Format::Avro => "avro",
+            Format::MessagePack => "msgpack",
+            Format::Sql => "sql",
+            Format::PgCopy => "pg-copy",
+        }
+    }
+
+    /// Whether rows are written as JSON objects, rather than delimited.
+    pub fn is_json(&self) -> bool {
+        matches!(self, Format::Json | Format::Jsonl)
+    }
+
+    /// Whether rows are written with a delimiter between fields.
+    pub fn is_delimited(&self) -> bool {
+        matches!(self, Format::Csv | Format::Tsv)
+    }
+
+    /// Whether rows are written as SQL, to load into a table.
+    pub fn is_sql(&self) -> bool {
+        matches!(self, Format::Sql | Format::PgCopy)
+    }
+
+    /// Whether the output is binary, rather than text.
+    pub fn is_binary(&self) -> bool {
+        matches!(self, Format::Avro | Format::MessagePack)
+    }
+}
+
+impl<'de> Deserialize<'de> for Format {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Format, D::Error> {
+        let name = String::deserialize(d)?;
+        match &*name {
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            "json" | "json-array" => Ok(Format::Json),
+            "jsonl" => Ok(Format::Jsonl),
+            "markdown" | "md" => Ok(Format::Markdown),
+            "table" => Ok(Format::Table),
+            "avro" => Ok(Format::Avro),
+            "msgpack" => Ok(Format::MessagePack),
+            "sql" => Ok(Format::Sql),
+            "pg-copy" => Ok(Format::PgCopy),
+            s => {
+                let msg = format!("Unknown output format '{}'.", s);
+                Err(D::Error::custom(msg))
+            }
+        }
+    }
+}
+
+/// A destination for split URL records, in one of the output formats.
+pub trait RecordWriter {
+    fn write_record(&mut self, record: &csv::StringRecord) -> io::Result<()>;
+
+    /// Write anything which closes the output, and flush it.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+pub struct CsvWriter<W: io::Write> {
+    wtr: csv::Writer<W>,
+}
+
+impl<W: io::Write> CsvWriter<W> {
+    pub fn new(wtr: csv::Writer<W>) -> Self {
+        CsvWriter { wtr }
+    }
+}
+
+impl<W: io::Write> RecordWriter for CsvWriter<W> {
+    fn write_record(&mut self, record: &csv::StringRecord) -> io::Result<()> {
+        Ok(self.wtr.write_record(record)?)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.wtr.flush()
+    }
+}
+
+/// Opens a new output for the numbered file, counting from 1.
+type OpenChunk<'a> = dyn FnMut(u64) -> io::Result<Box<dyn RecordWriter + Send + 'a>> + Send + 'a;
+
+/// Writes records to a series of outputs, with at most `rows_per_file`
+/// records in each, opening the next output once the last is full.
+///
+/// The first output is opened by the first record, or by `finish` when
+/// there are none, so that there's always at least one output.
+pub struct ChunkedWriter<'a> {
+    open: Box<OpenChunk<'a>>,
+    rows_per_file: u64,
+    current: Option<Box<dyn RecordWriter + Send + 'a>>,
+    rows: u64,
+    chunks: u64,
+}
+
+impl<'a> ChunkedWriter<'a> {
+    pub fn new<F>(rows_per_file: u64, open: F) -> Self
+    where
+        F: FnMut(u64) -> io::Result<Box<dyn RecordWriter + Send + 'a>> + Send + 'a,
+    {
+        ChunkedWriter {
+            open: Box::new(open),
+            rows_per_file,
+            current: None,
+            rows: 0,
+            chunks: 0,
+        }
+    }
+
+    fn next_chunk(&mut self) -> io::Result<&mut Box<dyn RecordWriter + Send + 'a>> {
+        if let Some(mut wtr) = self.current.take() {
+            wtr.finish()?;
+        }
+        self.chunks += 1;
+        self.rows = 0;
+        Ok(self.current.insert((self.open)(self.chunks)?))
+    }
+}
+
+impl RecordWriter for ChunkedWriter<'_> {
+    fn write_record(&mut self, record: &csv::StringRecord) -> io::Result<()> {
+        let wtr = match self.current {
+            Some(ref mut wtr) if self.rows < self.rows_per_file => wtr,
+            _ => self.next_chunk()?,
+        };
+        wtr.write_record(record)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.current {
+            Some(ref mut wtr) => wtr.finish(),
+            None => self.next_chunk()?.finish(),
+        }
+    }
+}
+
+/// Writes only the fields of each record at `indices`, in that order.
+pub struct SelectWriter<'a> {
+    wtr: Box<dyn RecordWriter + Send + 'a>,
+    indices: Vec<usize>,
+    record: csv::StringRecord,
+}
+
+impl<'a> SelectWriter<'a> {
+    pub fn new(wtr: Box<dyn RecordWriter + Send + 'a>, indices: Vec<usize>) -> Self {
+        SelectWriter {
+            wtr,
+            indices,
+            record: csv::StringRecord::new(),
+        }
+    }
+}
+
+impl RecordWriter for SelectWriter<'_> {
+    fn write_record(&mut self, record: &csv::StringRecord) -> io::Result<()> {
+        self.record.clear();
+        for &index in &self.indices {
+            self.record.push_field(record.get(index).unwrap_or(""));
+        }
+        self.wtr.write_record(&self.record)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.wtr.finish()
+    }
+}
+
+/// Drops the empty fields at the end of each record.
+pub struct TrimWriter<'a> {
+    wtr: Box<dyn RecordWriter + Send + 'a>,
+    record: csv::StringRecord,
+}
+
+impl<'a> TrimWriter<'a> {
+    pub fn new(wtr: Box<dyn RecordWriter + Send + 'a>) -> Self {
+        TrimWriter {
+            wtr,
+            record: csv::StringRecord::new(),
+        }
+    }
+}
+
+impl RecordWriter for TrimWriter<'_> {
+    fn write_record(&mut self, record: &csv::StringRecord) -> io::Result<()> {
+        let mut len = record.len();
+        while len > 0 && record[len - 1].is_empty() {
+            len -= 1;
+        }
+        self.record.clear();
+        self.record.extend(record.iter().take(len));
+        self.wtr.write_record(&self.record)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.wtr.finish()
+    }
+}
+
+/// Percent-encodes `%`, `"`, the delimiter and control characters in every
+/// field of each record, so that fields can be restored by percent-decoding
+/// them, and never need quoting.
+pub struct EncodeWriter<'a> {
+    wtr: Box<dyn RecordWriter + Send + 'a>,
+    delimiter: u8,
+    record: csv::StringRecord,
+}
+
+impl<'a> EncodeWriter<'a> {
+    pub fn new(wtr: Box<dyn RecordWriter + Send + 'a>, delimiter: u8) -> Self {
+        EncodeWriter {
+            wtr,
+            delimiter,
+            record: csv::StringRecord::new(),
+        }
+    }
+}
+
+impl RecordWriter for EncodeWriter<'_> {
+    fn write_record(&mut self, record: &csv::StringRecord) -> io::Result<()> {
+        self.record.clear();
+        for field in record {
+            self.record.push_field(&encode_field(field, self.delimiter));
+        }
+        self.wtr.write_record(&self.record)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.wtr.finish()
+    }
+}
+
+// Percent-encode each byte of the characters in the field which need it.
+fn encode_field(field: &str, delimiter: u8) -> String {
+    let mut encoded = String::with_capacity(field.len());
+    for c in field.chars() {
+        if c == '%' || c == '"' || c.is_control() || c as u32 == u32::from(delimiter) {
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+/// Writes records as a JSON array of objects, keyed by the output header.
+///
+/// Values are written as strings, unless the type of each column is given.
+pub struct JsonWriter<W: io::Write> {
+    wtr: W,
+    header: csv::StringRecord,
+    types: Option<Vec<ColumnType>>,
+    pretty: bool,
+    count: u64,
+}
+
+impl<W: io::Write> JsonWriter<W> {
+    pub fn new(
+        wtr: W,
+        header: csv::StringRecord,
+        types: Option<Vec<ColumnType>>,
+        pretty: bool,
+    ) -> Self {
+        JsonWriter {
+            wtr,
+            header,
+            types,
+            pretty,
+            count: 0,
+        }
+    }
+}
+
+impl<W: io::Write> RecordWriter for JsonWriter<W> {
+    fn write_record(&mut self, record: &csv::StringRecord) -> io::Result<()> {
+        let object = JsonObject {
+            header: &self.header,
+            types: self.types.as_deref(),
+            record,
+        };
+        let separator = if self.count == 0 { "[" } else { "," };
+        self.count += 1;
+
+        if self.pretty {
+            // Indent each object one level, so that the output matches
+            // pretty printing the whole array at once.
+            let text = serde_json::to_string_pretty(&object)?;
+            write!(self.wtr, "{}\n  {}", separator, text.replace('\n', "\n  "))
+        } else {
+            self.wtr.write_all(separator.as_bytes())?;
+            Ok(serde_json::to_writer(&mut self.wtr, &object)?)
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match (self.count, self.pretty) {
+            (0, _) => self.wtr.write_all(b"[]\n")?,
+            (_, true) => self.wtr.write_all(b"\n]\n")?,
+            (_, false) => self.wtr.write_all(b"]\n")?,
+        }
+        self.wtr.flush()
+    }
+}
+
+/// Writes records as JSON Lines, one object keyed by the output header on
+/// each line.
+///
+/// Values are written as strings, unless the type of each column is given.
+pub struct JsonlWriter<W: io::Write> {
+    wtr: W,
+    header: csv::StringRecord,
+    types: Option<Vec<ColumnType>>,
+}
+
+impl<W: io::Write> JsonlWriter<W> {
+    pub fn new(wtr: W, header: csv::StringRecord, types: Option<Vec<ColumnType>>) -> Self {
+        JsonlWriter { wtr, header, types }
+    }
+}
+
+impl<W: io::Write> RecordWriter for JsonlWriter<W> {
+    fn write_record(&mut self, record: &csv::StringRecord) -> io::Result<()> {
+        let object = JsonObject {
+            header: &self.header,
+            types: self.types.as_deref(),
+            record,
+        };
+        serde_json::to_writer(&mut self.wtr, &object)?;
+        self.wtr.write_all(b"\n")
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.wtr.flush()
+    }
+}
+
+/// Writes records as a GitHub flavored Markdown table, with the output
+/// header as the table's header, which Markdown requires.
+pub struct MarkdownWriter<W: io::Write> {
+    wtr: W,
+    header: csv::StringRecord,
+    started: bool,
+}
+
+impl<W: io::Write> MarkdownWriter<W> {
+    pub fn new(wtr: W, header: csv::StringRecord) -> Self {
+        MarkdownWriter {
+            wtr,
+            header,
+            started: false,
+        }
+    }
+
+    fn write_row<'r, I: IntoIterator<Item = &'r str>>(&mut self, fields: I) -> io::Result<()> {
+        let cells = fields.into_iter().map(markdown_cell).collect::<Vec<_>>();
+        writeln!(self.wtr, "| {} |", cells.join(" | "))
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        if !self.started {
+            self.started = true;
+            let header = self.header.clone();
+            self.write_row(&header)?;
+            writeln!(self.wtr, "|{}", " --- |".repeat(header.len()))?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: io::Write> RecordWriter for MarkdownWriter<W> {
+    fn write_record(&mut self, record: &csv::StringRecord) -> io::Result<()> {
+        self.start()?;
+        self.write_row(record)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.start()?;
+        self.wtr.flush()
+    }
+}
+
+/// When `--format table` colors its output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Color, D::Error> {
+        let name = String::deserialize(d)?;
+        match &*name {
+            "auto" => Ok(Color::Auto),
+            "always" => Ok(Color::Always),
+            "never" => Ok(Color::Never),
+            s => {
+                let msg = format!("Unknown color choice '{}'.", s);
+                Err(D::Error::custom(msg))
+            }
+        }
+    }
+}
+
+/// Writes records as a table aligned for reading in a terminal, with fields
+/// wider than `width` columns cut short.
+///
+/// Rows are kept until the end, since every row decides how wide each
+/// column is. With color, the header is bold and rows with an `error` red.
+pub struct TableWriter<W: io::Write> {
+    wtr: W,
+    header: csv::StringRecord,
+    headers: bool,
+    width: usize,
+    color: bool,
+    rows: Vec<csv::StringRecord>,
+}
+
+// The escape codes which color a line of the table, and reset it.
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+impl<W: io::Write> TableWriter<W> {
+    /// A table of at most `width` columns for each field, with a header
+    /// unless `headers` is false.
+    pub fn new(wtr: W, header: csv::StringRecord, headers: bool, width: usize) -> Self {
+        TableWriter {
+            wtr,
+            header,
+            headers,
+            width,
+            color: false,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Color the table with terminal escape codes.
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    // A field with its control characters escaped, cut short to the column
+    // width, ending with `…` if it was.
+    fn cell<'f>(&self, field: &'f str) -> Cow<'f, str> {
+        let field = escape_controls(field);
+        if field.width() <= self.width {
+            return field;
+        }
+        let mut cell = String::new();
+        let mut width = 0;
+        for c in field.chars() {
+            width += c.width().unwrap_or(0);
+            if width > self.width.saturating_sub(1) {
+                break;
+            }
+            cell.push(c);
+        }
+        cell.push('…');
+        Cow::Owned(cell)
+    }
+
+    fn write_line(
+        &mut self,
+        cells: &[Cow<str>],
+        widths: &[usize],
+        color: Option<&str>,
+    ) -> io::Result<()> {
+        // Padding is counted in display columns, which `format!` can't do.
+        let line = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, &width)| {
+                let padding = width.saturating_sub(cell.width());
+                format!("{}{}", cell, " ".repeat(padding))
+            })
+            .collect::<Vec<_>>()
+            .join("  ");
+        let line = line.trim_end();
+        match color.filter(|_| self.color) {
+            Some(color) => writeln!(self.wtr, "{}{}{}", color, line, RESET),
+            None => writeln!(self.wtr, "{}", line),
+        }
+    }
+}
+
+impl<W: io::Write> RecordWriter for TableWriter<W> {
+    fn write_record(&mut self, record: &csv::StringRecord) -> io::Result<()> {
+        self.rows.push(record.clone());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let header = self.header.clone();
+        let rows = std::mem::take(&mut self.rows);
+        let shown = self.headers.then_some(&header).into_iter().chain(&rows);
+        let cells = shown
+            .map(|row| row.iter().map(|field| self.cell(field)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        let mut widths = vec![0; header.len()];
+        for row in &cells {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.width());
+            }
+        }
+
+        let error = header.iter().position(|name| name == "error");
+        let mut cells = cells.iter();
+        if self.headers {
+            if let Some(row) = cells.next() {
+                self.write_line(row, &widths, Some(BOLD))?;
+            }
+            let rule = widths.iter().map(|&width| "-".repeat(width));
+            writeln!(self.wtr, "{}", rule.collect::<Vec<_>>().join("  "))?;
+        }
+        for (row, record) in cells.zip(&rows) {
+            let failed = error
+                .and_then(|i| record.get(i))
+                .is_some_and(|e| !e.is_empty());
+            self.write_line(row, &widths, failed.then_some(RED))?;
+        }
+        self.wtr.flush()
+    }
+}
+
+// Text with the characters which a terminal would act on, rather than show,
+// escaped, e.g. `\u{1b}` for the `ESC` which starts an escape sequence.
+// Bidirectional overrides are escaped too, since they reorder the text after
+// them.
+fn escape_controls(text: &str) -> Cow<'_, str> {
+    let escaped =
+        |c: char| c.is_control() || matches!(c, '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}');
+    if !text.chars().any(escaped) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped_text = String::with_capacity(text.len() + 8);
+    for c in text.chars() {
+        if escaped(c) {
+            escaped_text.extend(c.escape_unicode());
+        } else {
+            escaped_text.push(c);
+        }
+    }
+    Cow::Owned(escaped_text)
+}
+
+/// Writes records as an Avro object container file, with a schema made from
+/// the output header and the type of each column, and no compression.
+///
+/// Empty optional fields are `null`, and fields which don't parse as their
+/// column's type are kept as strings, as for `--json-types`.
+pub struct AvroWriter<W: io::Write> {
+    wtr: W,
+    header: csv::StringRecord,
+    types: Vec<ColumnType>,
+    sync: [u8; 16],
+    block: Vec<u8>,
+    count: usize,
+    started: bool,
+}
+
+// How many records are written in each block of an Avro file.
+const AVRO_BLOCK_RECORDS: usize = 1000;
+
+impl<W: io::Write> AvroWriter<W> {
+    pub fn new(wtr: W, header: csv::StringRecord, types: Vec<ColumnType>) -> Self {
+        AvroWriter {
+            wtr,
+            header,
+            types,
+            sync: avro_sync_marker(),
+            block: Vec::new(),
+            count: 0,
+            started: false,
+        }
+    }
+
+    /// The schema of the records, as JSON.
+    pub fn schema(&self) -> serde_json::Value {
+        let fields = self
+            .header
+            .iter()
+            .zip(&self.types)
+            .map(|(name, &kind)| {
+                serde_json::json!({
+                    "name": avro_name(name),
+                    "type": avro_type(kind),
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::json!({"type": "record", "name": "url", "fields": fields})
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+
+        let mut header = b"Obj\x01".to_vec();
+        avro_long(&mut header, 2);
+        avro_bytes(&mut header, b"avro.schema");
+        avro_bytes(&mut header, self.schema().to_string().as_bytes());
+        avro_bytes(&mut header, b"avro.codec");
+        avro_bytes(&mut header, b"null");
+        avro_long(&mut header, 0);
+        header.extend(self.sync);
+        self.wtr.write_all(&header)
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.count == 0 {
+            return Ok(());
+        }
+        let mut prefix = Vec::new();
+        avro_long(&mut prefix, self.count as i64);
+        avro_long(&mut prefix, self.block.len() as i64);
+        self.wtr.write_all(&prefix)?;
+        self.wtr.write_all(&self.block)?;
+        self.wtr.write_all(&self.sync)?;
+        self.block.clear();
+        self.count = 0;
+        Ok(())
+    }
+}
+
+impl<W: io::Write> RecordWriter for AvroWriter<W> {
+    fn write_record(&mut self, record: &csv::StringRecord) -> io::Result<()> {
+        self.start()?;
+        for (value, &kind) in record.iter().zip(&self.types) {
+            avro_value(&mut self.block, value, kind)?;
+        }
+        self.count += 1;
+        if self.count == AVRO_BLOCK_RECORDS {
+            self.write_block()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.start()?;
+        self.write_block()?;
+        self.wtr.flush()
+    }
+}
+
+// The Avro type of a column. Optional columns are unions with `null`, and
+// typed columns with a `string` for values which don't parse.
+fn avro_type(kind: ColumnType) -> serde_json::Value {
+    match kind {
+        ColumnType::Text => "string".into(),
+        ColumnType::OptionalText => serde_json::json!(["null", "string"]),
+        ColumnType::Integer => serde_json::json!(["null", "long", "string"]),
+        ColumnType::Boolean => serde_json::json!(["null", "boolean", "string"]),
+    }
+}
+
+// Append a field, encoded as a value of its column's Avro type. Fields are
+// converted as for `--json-types`, and integers too large for a long are an
+// error, rather than quietly kept as strings.
+fn avro_value(buf: &mut Vec<u8>, value: &str, kind: ColumnType) -> io::Result<()> {
+    match (kind, typed_value(value, kind)) {
+        (ColumnType::Text, _) => avro_bytes(buf, value.as_bytes()),
+        (_, serde_json::Value::Null) => avro_long(buf, 0),
+        (ColumnType::OptionalText, _) => {
+            avro_long(buf, 1);
+            avro_bytes(buf, value.as_bytes());
+        }
+        (_, serde_json::Value::Number(number)) => {
+            let number = number.as_i64().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is too large for an Avro long", number),
+                )
+            })?;
+            avro_long(buf, 1);
+            avro_long(buf, number);
+        }
+        (_, serde_json::Value::Bool(flag)) => {
+            avro_long(buf, 1);
+            buf.push(u8::from(flag));
+        }
+        _ => {
+            avro_long(buf, 2);
+            avro_bytes(buf, value.as_bytes());
+        }
+    }
+    Ok(())
+}
+
+// Append a long, zig-zag encoded as a variable length integer.
+fn avro_long(buf: &mut Vec<u8>, n: i64) {
+    let mut n = ((n << 1) ^ (n >> 63)) as u64;
+    while n >= 0x80 {
+        buf.push((n & 0x7f) as u8 | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+// Append bytes, or a string, after their length.
+fn avro_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    avro_long(buf, bytes.len() as i64);
+    buf.extend_from_slice(bytes);
+}
+
+// A column name as an Avro name, which may only have ASCII letters, digits
+// and `_`, and may not start with a digit.
+fn avro_name(name: &str) -> String {
+    let mut avro = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if !avro.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        avro.insert(0, '_');
+    }
+    avro
+}
+
+// The marker written between blocks, which should differ between files.
+fn avro_sync_marker() -> [u8; 16] {
+    let half = || RandomState::new().build_hasher().finish().to_le_bytes();
+    let mut sync = [0; 16];
+    sync[..8].copy_from_slice(&half());
+    sync[8..].copy_from_slice(&half());
+    sync
+}
+
+/// Writes records as a stream of MessagePack maps, keyed by the output
+/// header, one after another with nothing between them.
+///
+/// Values have the types described for `--json-types`.
+pub struct MessagePackWriter<W: io::Write> {
+    wtr: W,
+    header: csv::StringRecord,
+    types: Vec<ColumnType>,
+}
+
+impl<W: io::Write> MessagePackWriter<W> {
+    pub fn new(wtr: W, header: csv::StringRecord, types: Vec<ColumnType>) -> Self {
+        MessagePackWriter { wtr, header, types }
+    }
+}
+
+impl<W: io::Write> RecordWriter for MessagePackWriter<W> {
+    fn write_record(&mut self, record: &csv::StringRecord) -> io::Result<()> {
+        let mut buf = Vec::new();
+        msgpack_length(&mut buf, self.header.len(), [0x80, 0xde, 0xdf], 16);
+        for ((name, value), &kind) in self.header.iter().zip(record.iter()).zip(&self.types) {
+            msgpack_str(&mut buf, name);
+            match typed_value(value, kind) {
+                serde_json::Value::Null => buf.push(0xc0),
+                serde_json::Value::Bool(flag) => buf.push(if flag { 0xc3 } else { 0xc2 }),
+                // Integer columns only hold unsigned numbers.
+                serde_json::Value::Number(n) => match n.as_u64() {
+                    Some(n) => msgpack_uint(&mut buf, n),
+                    None => msgpack_str(&mut buf, &n.to_string()),
+                },
+                serde_json::Value::String(text) => msgpack_str(&mut buf, &text),
+                other => msgpack_str(&mut buf, &other.to_string()),
+            }
+        }
+        self.wtr.write_all(&buf)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.wtr.flush()
+    }
+}
+
+// Append the length of a map or string, with the marker for a short one,
+// which holds lengths below `short`, or for 16 or 32 bit lengths.
+fn msgpack_length(buf: &mut Vec<u8>, len: usize, markers: [u8; 3], short: usize) {
+    if len < short {
+        buf.push(markers[0] | len as u8);
+    } else if let Ok(len) = u16::try_from(len) {
+        buf.push(markers[1]);
+        buf.extend(len.to_be_bytes());
+    } else {
+        buf.push(markers[2]);
+        buf.extend((len as u32).to_be_bytes());
+    }
+}
+
+fn msgpack_str(buf: &mut Vec<u8>, text: &str) {
+    match u8::try_from(text.len()) {
+        // Strings of 32 to 255 bytes have their own 8 bit marker.
+        Ok(len) if len >= 32 => buf.extend([0xd9, len]),
+        _ => msgpack_length(buf, text.len(), [0xa0, 0xda, 0xdb], 32),
+    }
+    buf.extend(text.as_bytes());
+}
+
+fn msgpack_uint(buf: &mut Vec<u8>, n: u64) {
+    if n < 0x80 {
+        buf.push(n as u8);
+    } else if let Ok(n) = u8::try_from(n) {
+        buf.extend([0xcc, n]);
+    } else if let Ok(n) = u16::try_from(n) {
+        buf.push(0xcd);
+        buf.extend(n.to_be_bytes());
+    } else if let Ok(n) = u32::try_from(n) {
+        buf.push(0xce);
+        buf.extend(n.to_be_bytes());
+    } else {
+        buf.push(0xcf);
+        buf.extend(n.to_be_bytes());
+    }
+}
+
+/// Writes records as SQL to load them into `table`, with columns named by
+/// the output header: either an `INSERT` statement for each record, or, for
+/// Postgres, a `COPY ... FROM STDIN` of them all.
+///
+/// Empty optional fields are `NULL`, and numbers and booleans are written
+/// as such, unless they don't parse as their column's type.
+pub struct SqlWriter<W: io::Write> {
+    wtr: W,
+    table: String,
+    header: csv::StringRecord,
+    types: Vec<ColumnType>,
+    copy: bool,
+    started: bool,
+}
+
+impl<W: io::Write> SqlWriter<W> {
+    pub fn new(
+        wtr: W,
+        table: &str,
+        header: csv::StringRecord,
+        types: Vec<ColumnType>,
+        copy: bool,
+    ) -> Self {
+        SqlWriter {
+            wtr,
+            table: sql_identifier(table),
+            header,
+            types,
+            copy,
+            started: false,
+        }
+    }
+
+    fn columns(&self) -> String {
+        self.header
+            .iter()
+            .map(sql_identifier_part)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        if self.copy && !self.started {
+            writeln!(
+                self.wtr,
+                "COPY {} ({}) FROM STDIN;",
+                self.table,
+                self.columns()
+            )?;
+        }
+        self.started = true;
+        Ok(())
+    }
+}
+
+impl<W: io::Write> RecordWriter for SqlWriter<W> {
+    fn write_record(&mut self, record: &csv::StringRecord) -> io::Result<()> {
+        self.start()?;
+        let values = record.iter().zip(&self.types);
+        if self.copy {
+            let values = values.map(|(value, &kind)| match typed_value(value, kind) {
+                serde_json::Value::Null => "\\N".to_string(),
+                _ => copy_text(value),
+            });
+            writeln!(self.wtr, "{}", values.collect::<Vec<_>>().join("\t"))
+        } else {
+            let values = values.map(|(value, &kind)| sql_value(value, kind));
+            writeln!(
+                self.wtr,
+                "INSERT INTO {} ({}) VALUES ({});",
+                self.table,
+                self.columns(),
+                values.collect::<Vec<_>>().join(", ")
+            )
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.start()?;
+        if self.copy {
+            writeln!(self.wtr, "\\.")?;
+        }
+        self.wtr.flush()
+    }
+}
+
+// A field as an SQL literal of its column's type.
+fn sql_value(value: &str, kind: ColumnType) -> String {
+    match typed_value(value, kind) {
+        serde_json::Value::Null => "NULL".to_string(),
+        serde_json::Value::Bool(true) => "TRUE".to_string(),
+        serde_json::Value::Bool(false) => "FALSE".to_string(),
+        serde_json::Value::Number(n) => n.to_string(),
+        _ => format!("'{}'", value.replace('\'', "''")),
+    }
+}
+
+// A table name, which may be qualified by a schema, as `schema.table`, with
+// each part quoted.
+fn sql_identifier(name: &str) -> String {
+    name.split('.')
+        .map(sql_identifier_part)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn sql_identifier_part(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+// A field escaped for the text format of Postgres' `COPY`.
+fn copy_text(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => text.push_str("\\\\"),
+            '\t' => text.push_str("\\t"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+/// A format string for `--template`, with output columns named in braces,
+/// like `{registration}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Field(usize),
+}
+
+impl Template {
+    /// Parse a template, finding each column it names in `header`.
+    ///
+    /// `{{` and `}}` stand for literal braces, and `\t`, `\n` and `\\` for
+    /// a tab, a newline and a backslash, which are awkward to pass in a
+    /// shell.
+    pub fn parse(template: &str, header: &csv::StringRecord) -> Result<Template, String> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('{', Some('{')) | ('}', Some('}')) => {
+                    chars.next();
+                    text.push(c);
+                }
+                ('\\', Some(&escaped @ ('t' | 'n' | '\\'))) => {
+                    chars.next();
+                    text.push(match escaped {
+                        't' => '\t',
+                        'n' => '\n',
+                        _ => '\\',
+                    });
+                }
+                ('{', _) => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => {
+                                return Err(
+                                    "unmatched '{' in the template, where '{{' is a brace".into()
+                                )
+                            }
+                        }
+                    }
+                    match header.iter().position(|h| h == name) {
+                        Some(index) => {
+                            pieces.push(Piece::Text(std::mem::take(&mut text)));
+                            pieces.push(Piece::Field(index));
+                        }
+                        None => {
+                            return Err(format!(
+                                "no output column named '{}' in the template",
+                                name
+                            ))
+                        }
+                    }
+                }
+                ('}', _) => {
+                    return Err("unmatched '}' in the template, where '}}' is a brace".into())
+                }
+                (c, _) => text.push(c),
+            }
+        }
+        pieces.push(Piece::Text(text));
+        pieces.retain(|piece| *piece != Piece::Text(String::new()));
+        Ok(Template { pieces })
+    }
+
+    /// The template with the fields of a record filled in.
+    pub fn render(&self, record: &csv::StringRecord) -> String {
+        let mut rendered = String::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => rendered.push_str(text),
+                Piece::Field(index) => rendered.push_str(record.get(*index).unwrap_or("")),
+            }
+        }
+        rendered
+    }
+}
+
+/// Writes each record on its own line, rendered through a `Template`.
+pub struct TemplateWriter<W: io::Write> {
+    wtr: W,
+    template: Template,
+}
+
+impl<W: io::Write> TemplateWriter<W> {
+    pub fn new(wtr: W, template: Template) -> Self {
+        TemplateWriter { wtr, template }
+    }
+}
+
+impl<W: io::Write> RecordWriter for TemplateWriter<W> {
+    fn write_record(&mut self, record: &csv::StringRecord) -> io::Result<()> {
+        writeln!(self.wtr, "{}", self.template.render(record))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.wtr.flush()
+    }
+}
+
+// A field escaped for a Markdown table cell, which ends at a `|` or the end
+// of the line.
+fn markdown_cell(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace(['\n', '\r'], "<br>")
+}
+
+// A record serialized as an object, with its fields in header order.
+struct JsonObject<'a> {
+    header: &'a csv::StringRecord,
+    types: Option<&'a [ColumnType]>,
+    record: &'a csv::StringRecord,
+}
+
+impl Serialize for JsonObject<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let mut map = s.serialize_map(Some(self.header.len()))?;
+        for (i, (name, value)) in self.header.iter().zip(self.record.iter()).enumerate() {
+            match self.types.and_then(|types| types.get(i)) {
+                Some(&kind) => map.serialize_entry(name, &typed_value(value, kind))?,
+                None => map.serialize_entry(name, value)?,
+            }
+        }
+        map.end()
+    }
+}
+
+// Convert a field back to its type. Values which don't parse as their
+// type are kept as strings, rather than lost.
+fn typed_value(value: &str, kind: ColumnType) -> serde_json::Value {
+    match kind {
+        ColumnType::Text => value.into(),
+        _ if value.is_empty() => serde_json::Value::Null,
+        ColumnType::OptionalText => value.into(),
+        ColumnType::Integer => value.parse::<u64>().map_or(value.into(), Into::into),
+        ColumnType::Boolean => value.parse::<bool>().map_or(value.into(), Into::into),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn write_json(pretty: bool) -> String {
+        let mut out = Vec::new();
+        let header = csv::StringRecord::from(vec!["url", "scheme"]);
+        let mut wtr = JsonWriter::new(&mut out, header, None, pretty);
+        wtr.write_record(&csv::StringRecord::from(vec!["http://a", "http"]))
+            .unwrap();
+        wtr.write_record(&csv::StringRecord::from(vec!["ftp://b", "ftp"]))
+            .unwrap();
+        wtr.finish().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_json_writer() {
+        let compact = write_json(false);
+        assert_eq!(
+            compact,
+            "[{\"url\":\"http://a\",\"scheme\":\"http\"},{\"url\":\"ftp://b\",\"scheme\":\"ftp\"}]\n"
+        );
+
+        let pretty = write_json(true);
+        assert_eq!(
+            pretty,
+            "[\n  {\n    \"url\": \"http://a\",\n    \"scheme\": \"http\"\n  },\n  \
+             {\n    \"url\": \"ftp://b\",\n    \"scheme\": \"ftp\"\n  }\n]\n"
+        );
+    }
+
+    #[test]
+    fn test_jsonl_writer() {
+        let mut out = Vec::new();
+        let header = csv::StringRecord::from(vec!["url", "port"]);
+        let types = vec![ColumnType::Text, ColumnType::Integer];
+        let mut wtr = JsonlWriter::new(&mut out, header, Some(types));
+        wtr.write_record(&csv::StringRecord::from(vec!["http://a:81", "81"]))
+            .unwrap();
+        wtr.write_record(&csv::StringRecord::from(vec!["ftp://b", ""]))
+            .unwrap();
+        wtr.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"url\":\"http://a:81\",\"port\":81}\n{\"url\":\"ftp://b\",\"port\":null}\n"
+        );
+
+        let mut out = Vec::new();
+        let header = csv::StringRecord::from(vec!["url"]);
+        JsonlWriter::new(&mut out, header, None).finish().unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_markdown_writer() {
+        let mut out = Vec::new();
+        let header = csv::StringRecord::from(vec!["url", "query"]);
+        let mut wtr = MarkdownWriter::new(&mut out, header.clone());
+        wtr.write_record(&csv::StringRecord::from(vec!["http://a/?a|b", "a|b"]))
+            .unwrap();
+        wtr.write_record(&csv::StringRecord::from(vec!["ftp://b", ""]))
+            .unwrap();
+        wtr.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "| url | query |\n| --- | --- |\n| http://a/?a\\|b | a\\|b |\n| ftp://b |  |\n"
+        );
+
+        let mut out = Vec::new();
+        MarkdownWriter::new(&mut out, header).finish().unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "| url | query |\n| --- | --- |\n"
+        );
+
+        assert_eq!(markdown_cell("a\\b\nc"), "a\\\\b<br>c");
+    }
+
+    #[test]
+    fn test_table_writer() {
+        let write_table = |headers: bool, color: bool| {
+            let mut out = Vec::new();
+            let header = csv::StringRecord::from(vec!["url", "port", "error"]);
+            let mut wtr = TableWriter::new(&mut out, header, headers, 10).color(color);
+            wtr.write_record(&csv::StringRecord::from(vec!["http://a:8080", "8080", ""]))
+                .unwrap();
+            wtr.write_record(&csv::StringRecord::from(vec!["b", "", "bad"]))
+                .unwrap();
+            wtr.finish().unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            write_table(true, false),
+            "url         port  error\n\
+             ----------  ----  -----\n\
+             http://a:…  8080\n\
+             b                 bad\n"
+        );
+        assert_eq!(
+            write_table(false, false),
+            "http://a:…  8080\n\
+             b                 bad\n"
+        );
+        assert_eq!(
+            write_table(true, true),
+            "\x1b[1murl         port  error\x1b[0m\n\
+             ----------  ----  -----\n\
+             http://a:…  8080\n\
+             \x1b[31mb                 bad\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn test_table_display_width() {
+        let mut out = Vec::new();
+        let header = csv::StringRecord::from(vec!["host", "n"]);
+        let mut wtr = TableWriter::new(&mut out, header, false, 6);
+        wtr.write_record(&csv::StringRecord::from(vec!["例え.jp", "1"]))
+            .unwrap();
+        wtr.write_record(&csv::StringRecord::from(vec!["日本語の例", "2"]))
+            .unwrap();
+        wtr.write_record(&csv::StringRecord::from(vec!["a.jp", "3"]))
+            .unwrap();
+        wtr.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "例え.…  1\n日本…   2\na.jp    3\n"
+        );
+
+        let mut out = Vec::new();
+        let header = csv::StringRecord::from(vec!["fragment_decoded", "n"]);
+        let mut wtr = TableWriter::new(&mut out, header, false, 12);
+        wtr.write_record(&csv::StringRecord::from(vec!["\x1b[2J", "1"]))
+            .unwrap();
+        wtr.write_record(&csv::StringRecord::from(vec!["\u{1fa70}e\u{301}", "2"]))
+            .unwrap();
+        wtr.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\\u{1b}[2J  1\n\u{1fa70}e\u{301}        2\n"
+        );
+        assert_eq!(escape_controls("a\u{202e}b"), "a\\u{202e}b");
+    }
+
+    #[test]
+    fn test_avro_writer() {
+        let mut out = Vec::new();
+        let header = csv::StringRecord::from(vec!["url", "port"]);
+        let types = vec![ColumnType::Text, ColumnType::Integer];
+        let mut wtr = AvroWriter::new(&mut out, header, types);
+        let schema = wtr.schema().to_string();
+        let sync = wtr.sync;
+        wtr.write_record(&csv::StringRecord::from(vec!["http://a", "81"]))
+            .unwrap();
+        wtr.write_record(&csv::StringRecord::from(vec!["b", ""]))
+            .unwrap();
+        wtr.finish().unwrap();
+
+        assert_eq!(
+            schema,
+            r#"{"fields":[{"name":"url","type":"string"},{"name":"port","type":["null","long","string"]}],"name":"url","type":"record"}"#
+        );
+        let mut expected = b"Obj\x01\x04\x16avro.schema".to_vec();
+        avro_bytes(&mut expected, schema.as_bytes());
+        expected.extend(b"\x14avro.codec\x08null\x00");
+        expected.extend(sync);
+        // Two records in 15 bytes: `http://a` with port 81, and `b` with none.
+        expected.extend(b"\x04\x1e\x10http://a\x02\xa2\x01\x02b\x00");
+        expected.extend(sync);
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_avro_encoding() {
+        let encode = |n| {
+            let mut buf = Vec::new();
+            avro_long(&mut buf, n);
+            buf
+        };
+        assert_eq!(encode(0), [0x00]);
+        assert_eq!(encode(-1), [0x01]);
+        assert_eq!(encode(1), [0x02]);
+        assert_eq!(encode(64), [0x80, 0x01]);
+        assert_eq!(encode(-65), [0x81, 0x01]);
+
+        let value = |value, kind| {
+            let mut buf = Vec::new();
+            avro_value(&mut buf, value, kind).map(|()| buf)
+        };
+        assert_eq!(
+            value("8080", ColumnType::Integer).unwrap(),
+            [0x02, 0xa0, 0x7e]
+        );
+        assert_eq!(
+            value("-1", ColumnType::Integer).unwrap(),
+            [0x04, 0x04, b'-', b'1']
+        );
+        assert!(value("18446744073709551615", ColumnType::Integer).is_err());
+
+        assert_eq!(avro_name("a_registration"), "a_registration");
+        assert_eq!(avro_name("1st-url"), "_1st_url");
+    }
+
+    #[test]
+    fn test_msgpack_writer() {
+        let mut out = Vec::new();
+        let header = csv::StringRecord::from(vec!["url", "port", "secure"]);
+        let types = vec![ColumnType::Text, ColumnType::Integer, ColumnType::Boolean];
+        let mut wtr = MessagePackWriter::new(&mut out, header, types);
+        wtr.write_record(&csv::StringRecord::from(vec![
+            "https://a:8443",
+            "8443",
+            "true",
+        ]))
+        .unwrap();
+        wtr.write_record(&csv::StringRecord::from(vec!["b", "", "x"]))
+            .unwrap();
+        wtr.finish().unwrap();
+
+        let mut expected =
+            b"\x83\xa3url\xaehttps://a:8443\xa4port\xcd\x20\xfb\xa6secure\xc3".to_vec();
+        expected.extend(b"\x83\xa3url\xa1b\xa4port\xc0\xa6secure\xa1x");
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_msgpack_encoding() {
+        let mut buf = Vec::new();
+        msgpack_str(&mut buf, &"a".repeat(40));
+        assert_eq!(buf[..2], [0xd9, 40]);
+
+        let mut buf = Vec::new();
+        msgpack_str(&mut buf, &"a".repeat(300));
+        assert_eq!(buf[..3], [0xda, 0x01, 0x2c]);
+
+        let mut buf = Vec::new();
+        msgpack_length(&mut buf, 20, [0x80, 0xde, 0xdf], 16);
+        assert_eq!(buf, [0xde, 0x00, 0x14]);
+
+        let mut buf = Vec::new();
+        msgpack_uint(&mut buf, 200);
+        msgpack_uint(&mut buf, 70000);
+        assert_eq!(buf, [0xcc, 200, 0xce, 0x00, 0x01, 0x11, 0x70]);
+    }
+
+    fn write_sql(copy: bool) -> String {
+        let mut out = Vec::new();
+        let header = csv::StringRecord::from(vec!["url", "port", "query"]);
+        let types = vec![
+            ColumnType::Text,
+            ColumnType::Integer,
+            ColumnType::OptionalText,
+        ];
+        let mut wtr = SqlWriter::new(&mut out, "web.urls", header, types, copy);
+        wtr.write_record(&csv::StringRecord::from(vec![
+            "http://a:81/?q='1'",
+            "81",
+            "q='1'",
+        ]))
+        .unwrap();
+        wtr.write_record(&csv::StringRecord::from(vec!["b\tc\\", "", ""]))
+            .unwrap();
+        wtr.finish().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_sql_writer() {
+        assert_eq!(
+            write_sql(false),
+            "INSERT INTO \"web\".\"urls\" (\"url\", \"port\", \"query\") \
+             VALUES ('http://a:81/?q=''1''', 81, 'q=''1''');\n\
+             INSERT INTO \"web\".\"urls\" (\"url\", \"port\", \"query\") \
+             VALUES ('b\tc\\', NULL, NULL);\n"
+        );
+        assert_eq!(
+            write_sql(true),
+            "COPY \"web\".\"urls\" (\"url\", \"port\", \"query\") FROM STDIN;\n\
+             http://a:81/?q='1'\t81\tq='1'\n\
+             b\\tc\\\\\t\\N\t\\N\n\
+             \\.\n"
+        );
+        assert_eq!(sql_identifier_part("a\"b"), "\"a\"\"b\"");
+    }
+
+    #[test]
+    fn test_template() {
+        let header = csv::StringRecord::from(vec!["registration", "path"]);
+        let record = csv::StringRecord::from(vec!["example.com", "/a"]);
+
+        let template = Template::parse("{registration}\\t{path}", &header).unwrap();
+        assert_eq!(template.render(&record), "example.com\t/a");
+
+        let template = Template::parse("{{{path}}} \\\\n{registration}", &header).unwrap();
+        assert_eq!(template.render(&record), "{/a} \\nexample.com");
+
+        assert_eq!(
+            Template::parse("{host}", &header),
+            Err("no output column named 'host' in the template".to_string())
+        );
+        assert!(Template::parse("{path", &header).is_err());
+        assert!(Template::parse("path}", &header).is_err());
+
+        let mut out = Vec::new();
+        let template = Template::parse("{path}", &header).unwrap();
+        let mut wtr = TemplateWriter::new(&mut out, template);
+        wtr.write_record(&record).unwrap();
+        wtr.finish().unwrap();
+        assert_eq!(out, b"/a\n");
+    }
+
+    // Records the rows written to each numbered output.
+    struct Recorder {
+        chunk: u64,
+        log: Arc<Mutex<Vec<(u64, String)>>>,
+    }
+
+    impl RecordWriter for Recorder {
+        fn write_record(&mut self, record: &csv::StringRecord) -> io::Result<()> {
+            let row = record.iter().collect::<Vec<_>>().join(",");
+            self.log.lock().unwrap().push((self.chunk, row));
+            Ok(())
+        }
+
+        fn finish(&mut self) -> io::Result<()> {
+            self.log
+                .lock()
+                .unwrap()
+                .push((self.chunk, "end".to_string()));
+            Ok(())
+        }
+    }
+
+    fn write_chunks(rows: &[&str]) -> Vec<(u64, String)> {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let opened = Arc::clone(&log);
+        let mut wtr = ChunkedWriter::new(2, move |chunk| {
+            let log = Arc::clone(&opened);
+            Ok(Box::new(Recorder { chunk, log }) as Box<dyn RecordWriter + Send>)
+        });
+        for row in rows {
+            wtr.write_record(&csv::StringRecord::from(vec![*row]))
+                .unwrap();
+        }
+        wtr.finish().unwrap();
+        let log = log.lock().unwrap();
+        log.clone()
+    }
+
+    #[test]
+    fn test_chunked_writer() {
+        let log = write_chunks(&["a", "b", "c"]);
+        let expected = [(1, "a"), (1, "b"), (1, "end"), (2, "c"), (2, "end")];
+        assert_eq!(
+            log,
+            expected
+                .iter()
+                .map(|&(chunk, row)| (chunk, row.to_string()))
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(write_chunks(&[]), vec![(1, "end".to_string())]);
+    }
+
+    #[test]
+    fn test_select_writer() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Recorder {
+            chunk: 1,
+            log: Arc::clone(&log),
+        };
+        let mut wtr = SelectWriter::new(Box::new(recorder), vec![2, 0]);
+        wtr.write_record(&csv::StringRecord::from(vec!["a", "b", "c"]))
+            .unwrap();
+        wtr.finish().unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![(1, "c,a".to_string()), (1, "end".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_trim_writer() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Recorder {
+            chunk: 1,
+            log: Arc::clone(&log),
+        };
+        let mut wtr = TrimWriter::new(Box::new(recorder));
+        for row in [vec!["a", "", "b", "", ""], vec!["", ""], vec!["a", "b"]] {
+            wtr.write_record(&csv::StringRecord::from(row)).unwrap();
+        }
+        let rows = log
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, row)| row.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(rows, vec!["a,,b", "", "a,b"]);
+    }
+
+    #[test]
+    fn test_encode_field() {
+        assert_eq!(
+            encode_field("https://example.com/a", b','),
+            "https://example.com/a"
+        );
+        assert_eq!(encode_field("a,b\tc\nd%20", b','), "a%2Cb%09c%0Ad%2520");
+        assert_eq!(encode_field("a,b|c", b'|'), "a,b%7Cc");
+        assert_eq!(encode_field("a\"b", b','), "a%22b");
+        assert_eq!(encode_field("caf\u{e9}\u{85}", b','), "caf\u{e9}%C2%85");
+
+        let field = "x,\r\n%y";
+        let encoded = encode_field(field, b',');
+        let decoded = percent_encoding::percent_decode_str(&encoded)
+            .decode_utf8()
+            .unwrap();
+        assert_eq!(decoded, field);
+    }
+
+    #[test]
+    fn test_typed_value() {
+        assert_eq!(typed_value("", ColumnType::Text), "");
+        assert_eq!(
+            typed_value("", ColumnType::OptionalText),
+            serde_json::Value::Null
+        );
+        assert_eq!(typed_value("8080", ColumnType::Integer), 8080);
+        assert_eq!(
+            typed_value("", ColumnType::Integer),
+            serde_json::Value::Null
+        );
+        assert_eq!(typed_value("true", ColumnType::Boolean), true);
+        assert_eq!(typed_value("yes", ColumnType::Boolean), "yes");
+    }
+}
@@ -1,4 +1,6 @@
+use std::collections::HashMap;
 use std::env;
+use std::io::Write;
 use std::path::PathBuf;
 use std::process;
 
@@ -21,6 +23,1400 @@ fn test_command() {
     assert!(output.status.success());
 }
 
+#[test]
+fn test_emit_contract() {
+    let output = process::Command::new(get_workdir().join("urlsplit"))
+        .arg("--emit-contract")
+        .output()
+        .expect("Failed to execute urlsplit");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        include_str!("out.csv")
+    );
+}
+
+#[test]
+fn test_latin1_encoding() {
+    let output = urlsplit_stdin(
+        &["--encoding", "latin1"],
+        b"URL\nhttp://caf\xe9.example.com/\n",
+    );
+
+    assert!(output.status.success());
+    let rows = records(&output.stdout);
+    assert_eq!(rows[0]["url"], "http://caf\u{e9}.example.com/");
+    assert_eq!(rows[0]["hostname"], "xn--caf-dma.example.com");
+    assert_eq!(rows[0]["subdomain"], "caf\u{e9}");
+}
+
+#[test]
+fn test_latin1_output_encoding() {
+    let output = urlsplit_stdin(
+        &["--output-encoding", "latin1", "-n"],
+        "http://caf\u{e9}.example.com/\nhttp://\u{4e2d}.example.com/\n".as_bytes(),
+    );
+    assert!(output.status.success());
+    assert!(output.stdout.starts_with(b"http://caf\xe9.example.com/,"));
+    assert!(output
+        .stdout
+        .split(|&b| b == b'\n')
+        .nth(1)
+        .expect("second row")
+        .starts_with(b"http://?.example.com/,"));
+
+    let output = urlsplit_stdin(
+        &["--output-encoding", "latin1", "--strict-encoding", "-n"],
+        "http://\u{4e2d}.example.com/\n".as_bytes(),
+    );
+    assert!(!output.status.success());
+    assert_eq!(output.stdout, b"");
+}
+
+#[test]
+fn test_no_error_column() {
+    let output = urlsplit_stdin(
+        &["--no-error-column"],
+        b"URL\nnot-a-url\nhttps://example.com\n",
+    );
+    assert!(output.status.success());
+    let rows = records(&output.stdout);
+    assert_eq!(rows.len(), 2);
+    assert!(rows.iter().all(|row| !row.contains_key("error")));
+    assert_eq!(rows[0]["scheme"], "");
+    assert_eq!(rows[1]["registration"], "example.com");
+}
+
+#[test]
+fn test_max_records() {
+    let output = urlsplit_stdin(
+        &["--max-records", "2"],
+        b"URL\nhttps://a.example.com\nhttps://b.example.com\nhttps://c.example.com\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(records(&output.stdout).len(), 2);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "stopped after processing 2 records\n"
+    );
+
+    // The input ending at the limit wasn't cut short.
+    let output = urlsplit_stdin(
+        &["--max-records", "2"],
+        b"URL\nhttps://a.example.com\nhttps://b.example.com\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(records(&output.stdout).len(), 2);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
+
+#[test]
+fn test_debug_timing() {
+    let input = b"URL\nhttps://example.com\n";
+
+    let output = urlsplit_stdin(&["--debug-timing"], input);
+    assert!(output.status.success());
+    let rows = records(&output.stdout);
+    assert!(rows[0]["parse_micros"].parse::<u64>().is_ok());
+
+    let output = urlsplit_stdin(&[], input);
+    assert!(!records(&output.stdout)[0].contains_key("parse_micros"));
+}
+
+#[test]
+fn test_url_columns() {
+    let output = urlsplit_stdin(
+        &["-q", "--url-columns", "dest_url,source_url"],
+        b"id,source_url,dest_url\n1,https://a.example.com/x,http://b.example.org/y\n",
+    );
+    assert!(output.status.success());
+    let rows = records(&output.stdout);
+    assert_eq!(rows[0]["source_url_hostname"], "a.example.com");
+    assert_eq!(rows[0]["dest_url_hostname"], "b.example.org");
+    assert_eq!(rows[0]["dest_url_path"], "/y");
+
+    let output = urlsplit_stdin(&["--url-columns", "missing"], b"url\n");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_detect_url_column() {
+    let output = urlsplit_stdin(&[], b"id,Link,other\n1,https://example.com/a,x\n");
+    assert!(output.status.success());
+    let rows = records(&output.stdout);
+    assert_eq!(rows[0]["url"], "https://example.com/a");
+
+    let output = urlsplit_stdin(&[], b"address,id\nhttps://example.com/a,1\n");
+    assert_eq!(records(&output.stdout)[0]["url"], "https://example.com/a");
+}
+
+#[test]
+fn test_explode_subdomains() {
+    let output = urlsplit_stdin(
+        &["--explode-subdomains"],
+        b"URL\nhttps://a.b.example.com/x\nhttps://example.org\nnot-a-url\n",
+    );
+    assert!(output.status.success());
+    let rows = records(&output.stdout);
+    let labels = rows
+        .iter()
+        .map(|row| {
+            (
+                row["registration"].as_str(),
+                row["label_index"].as_str(),
+                row["label"].as_str(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        labels,
+        vec![
+            ("example.com", "0", "a"),
+            ("example.com", "1", "b"),
+            ("example.org", "", ""),
+            ("", "", ""),
+        ]
+    );
+
+    let output = urlsplit_stdin(&["--explode-subdomains", "--explode-path"], b"URL\n");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_host_only() {
+    let output = urlsplit_stdin(
+        &["--host-only"],
+        b"URL\nhttps://www.example.co.uk/a\nnot-a-url\nhttp://b.example.com\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "example.co.uk\n\"\"\nexample.com\n"
+    );
+}
+
+#[test]
+fn test_count_distinct() {
+    let output = urlsplit_stdin(
+        &["--count-distinct", "registration"],
+        b"URL\nhttps://a.example.com\nhttps://b.example.com\nhttps://example.org\nnot-a-url\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(records(&output.stdout).len(), 4);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "distinct registration: 2\n"
+    );
+
+    let output = urlsplit_stdin(&["--count-distinct", "missing"], b"URL\n");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_schemes_report() {
+    let report = env::temp_dir().join(format!("urlsplit-schemes-{}.csv", process::id()));
+    let output = urlsplit_stdin(
+        &["--schemes-report", report.to_str().unwrap()],
+        b"URL\nhttps://a.example.com\nftp://b.example.com\nhttps://c.example.com\nnot-a-url\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(records(&output.stdout).len(), 4);
+
+    let tally = std::fs::read_to_string(&report).expect("schemes report");
+    std::fs::remove_file(&report).expect("remove schemes report");
+    assert_eq!(tally, "scheme,count\n,1\nftp,1\nhttps,2\n");
+}
+
+#[test]
+fn test_report() {
+    let report = env::temp_dir().join(format!("urlsplit-report-{}.json", process::id()));
+    let output = urlsplit_stdin(
+        &[
+            "--report",
+            report.to_str().unwrap(),
+            "--count-distinct",
+            "registration",
+            "--explode-path",
+        ],
+        b"URL\nhttps://a.example.com/x/y\nhttp://example.org\nnot-a-url\nhttps://b.example.com\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(records(&output.stdout).len(), 5);
+
+    let text = std::fs::read_to_string(&report).expect("report");
+    std::fs::remove_file(&report).expect("remove report");
+    let summary: serde_json::Value = serde_json::from_str(&text).expect("JSON report");
+    assert_eq!(
+        summary,
+        serde_json::json!({
+            "records": 4,
+            "urls": 4,
+            "errors": 1,
+            "rows": 5,
+            "schemes": {"": 1, "http": 1, "https": 2},
+            "suffixes": {"": 1, "com": 2, "org": 1},
+            "distinct": {"registration": 2},
+        })
+    );
+
+    // Each URL column's URL counts, not just each record.
+    let output = urlsplit_stdin(
+        &["--report", report.to_str().unwrap(), "--url-columns", "a,b"],
+        b"a,b\nhttps://example.com,https://example.org\n",
+    );
+    assert!(output.status.success());
+    let text = std::fs::read_to_string(&report).expect("report");
+    std::fs::remove_file(&report).expect("remove report");
+    let summary: serde_json::Value = serde_json::from_str(&text).expect("JSON report");
+    assert_eq!(summary["records"], 1);
+    assert_eq!(summary["urls"], 2);
+}
+
+#[test]
+fn test_explode_path() {
+    let output = urlsplit_stdin(
+        &["--explode-path"],
+        b"URL\nhttps://example.com/a/b%20c\nhttps://example.org\nnot-a-url\n",
+    );
+    assert!(output.status.success());
+    let rows = records(&output.stdout);
+    let segments = rows
+        .iter()
+        .map(|row| {
+            (
+                row["hostname"].as_str(),
+                row["segment_index"].as_str(),
+                row["segment"].as_str(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        segments,
+        vec![
+            ("example.com", "0", "a"),
+            ("example.com", "1", "b c"),
+            ("example.org", "0", ""),
+            ("", "", ""),
+        ]
+    );
+
+    let output = urlsplit_stdin(&["--explode-path", "--url-columns", "a,b"], b"a,b\n");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_count_scheme_host_pairs() {
+    let output = urlsplit_stdin(
+        &["--count-scheme-host-pairs"],
+        b"URL\nhttps://example.com/a\nhttp://example.com\nhttps://example.com/b\nmailto:a@example.com\nnot-a-url\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(records(&output.stdout).len(), 5);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "distinct scheme and host pairs: 3\n       2 https://example.com\n       \
+         1 http://example.com\n       1 mailto:\n"
+    );
+}
+
+#[test]
+fn test_group_by() {
+    let output = urlsplit_stdin(
+        &["--group-by", "registration"],
+        b"URL\nhttps://b.example.org\nhttps://a.example.com\nhttps://example.org\nhttps://c.example.com\nhttps://example.net\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "registration,count\nexample.com,2\nexample.org,2\nexample.net,1\n"
+    );
+
+    let output = urlsplit_stdin(&["--group-by", "missing"], b"URL\n");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_histogram() {
+    let output = urlsplit_stdin(
+        &["--histogram", "suffix"],
+        b"URL\nhttps://a.example.com\nhttps://example.org\nhttps://b.example.com\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(records(&output.stdout).len(), 3);
+    let stderr = String::from_utf8(output.stderr).expect("Valid utf-8 chart from urlsplit");
+    assert!(stderr.starts_with("com #"), "{}", stderr);
+    assert!(stderr.contains("\norg #"), "{}", stderr);
+
+    let output = urlsplit_stdin(&["--histogram", "missing"], b"URL\n");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_component_counts() {
+    let report = env::temp_dir().join(format!("urlsplit-components-{}.json", process::id()));
+    let output = urlsplit_stdin(
+        &["--component-counts", "--report", report.to_str().unwrap()],
+        b"URL\nhttps://example.com/#top\nhttps://example.org\nhttps://example.net\nhttps://example.com\n",
+    );
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).expect("Valid utf-8 counts from urlsplit");
+    assert!(
+        stderr.starts_with("columns with a value, of 4 rows:\n"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("\n 100.0% url\n"), "{}", stderr);
+    assert!(stderr.contains("\n  25.0% fragment\n"), "{}", stderr);
+    assert!(stderr.contains("\n   0.0% query\n"), "{}", stderr);
+
+    let text = std::fs::read_to_string(&report).expect("report");
+    std::fs::remove_file(&report).expect("remove report");
+    let report: serde_json::Value = serde_json::from_str(&text).expect("JSON report");
+    assert_eq!(report["components"]["fragment"], 1);
+    assert_eq!(report["components"]["hostname"], 4);
+}
+
+#[test]
+fn test_component_counts_of_written_columns() {
+    let input = b"URL\nhttps://example.com/#top\nhttps://example.org\nhttps://example.com\n";
+    let output = urlsplit_stdin(
+        &["--component-counts", "--fields-regex", "^(url|fragment)$"],
+        input,
+    );
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr,
+        "columns with a value, of 3 rows:\n 100.0% url\n  33.3% fragment\n"
+    );
+
+    let output = urlsplit_stdin(&["--component-counts", "--group-by", "hostname"], input);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr,
+        "columns with a value, of 2 rows:\n 100.0% hostname\n 100.0% count\n"
+    );
+}
+
+#[test]
+fn test_dedupe_window() {
+    let output = urlsplit_stdin(
+        &["--dedupe-window", "1", "--debug-timing"],
+        b"URL\nhttps://a.example\nhttps://a.example\nhttps://b.example\nhttps://a.example\n",
+    );
+    assert!(output.status.success());
+    let urls = records(&output.stdout)
+        .iter()
+        .map(|row| row["url"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        urls,
+        vec![
+            "https://a.example",
+            "https://b.example",
+            "https://a.example"
+        ]
+    );
+
+    let output = urlsplit_stdin(&["--dedupe-window", "0"], b"URL\nhttps://a.example\n");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_jobs() {
+    let mut input = b"URL\n".to_vec();
+    for i in 0..500 {
+        input.extend(format!("https://host{}.example.com/{}\nnot-a-url-{}\n", i, i, i).bytes());
+    }
+    let sequential = urlsplit_stdin(&[], &input);
+
+    let output = urlsplit_stdin(&["--jobs", "4"], &input);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, sequential.stdout);
+
+    let output = urlsplit_stdin(&["--jobs", "4", "--unordered"], &input);
+    assert!(output.status.success());
+    let mut rows = records(&output.stdout);
+    let mut expected = records(&sequential.stdout);
+    let url = |row: &HashMap<String, String>| row["url"].clone();
+    rows.sort_by_key(url);
+    expected.sort_by_key(url);
+    assert_eq!(rows, expected);
+
+    let output = urlsplit_stdin(&["--jobs", "2", "--max-records", "3"], &input);
+    assert_eq!(records(&output.stdout).len(), 3);
+
+    let output = urlsplit_stdin(&["--jobs", "3", "--queue-depth", "1"], &input);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, sequential.stdout);
+
+    let output = urlsplit_stdin(&["--jobs", "0"], &input);
+    assert!(!output.status.success());
+
+    let output = urlsplit_stdin(&["--jobs", "2", "--queue-depth", "0"], &input);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_secret_params() {
+    let input = b"URL\nhttps://example.com/?token=x\nhttps://example.com/?sig=x\n";
+
+    let output = urlsplit_stdin(&[], input);
+    let rows = records(&output.stdout);
+    assert_eq!(rows[0]["has_secret_param"], "true");
+    assert_eq!(rows[1]["has_secret_param"], "false");
+
+    let output = urlsplit_stdin(&["--secret-params", "sig,auth"], input);
+    let rows = records(&output.stdout);
+    assert_eq!(rows[0]["has_secret_param"], "true");
+    assert_eq!(rows[1]["has_secret_param"], "true");
+}
+
+#[test]
+fn test_shortener_list() {
+    let list = env::temp_dir().join(format!("urlsplit-shorteners-{}.txt", process::id()));
+    std::fs::write(&list, "# Internal shorteners\nexample.net\n\n").expect("write list");
+    let output = urlsplit_stdin(
+        &["--shortener-list", list.to_str().unwrap()],
+        b"URL\nhttps://go.example.net/a\nhttps://bit.ly/b\nhttps://example.com/c\n",
+    );
+    std::fs::remove_file(&list).expect("remove list");
+    assert!(output.status.success());
+    let shorteners = records(&output.stdout)
+        .iter()
+        .map(|row| row["is_shortener"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(shorteners, vec!["true", "true", "false"]);
+
+    let output = urlsplit_stdin(&["--shortener-list", "/nonexistent/list"], b"URL\n");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_sort_by() {
+    let input = b"URL\nhttps://b.example.org/1\nhttps://a.example.com/2\nhttps://example.org/3\nhttps://example.net/4\n";
+    let paths = |output: process::Output| {
+        records(&output.stdout)
+            .into_iter()
+            .map(|row| row["path"].clone())
+            .collect::<Vec<_>>()
+    };
+
+    let output = urlsplit_stdin(&["--sort-by", "registration"], input);
+    assert!(output.status.success());
+    assert_eq!(paths(output), vec!["/2", "/4", "/1", "/3"]);
+
+    let output = urlsplit_stdin(&["--sort-by", "registration", "--reverse"], input);
+    assert_eq!(paths(output), vec!["/1", "/3", "/4", "/2"]);
+
+    let output = urlsplit_stdin(&["--sort-by", "missing"], input);
+    assert!(!output.status.success());
+
+    let stream = |window| {
+        let args = ["--sort-by", "registration", "--stream-sort-buffer", window];
+        paths(urlsplit_stdin(&args, input))
+    };
+    assert_eq!(stream("1"), vec!["/2", "/1", "/4", "/3"]);
+    assert_eq!(stream("4"), vec!["/2", "/4", "/1", "/3"]);
+
+    let output = urlsplit_stdin(&["--stream-sort-buffer", "4"], input);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_invalid_output() {
+    let invalid = env::temp_dir().join(format!("urlsplit-invalid-{}.txt", process::id()));
+    let output = urlsplit_stdin(
+        &["--invalid-output", invalid.to_str().unwrap()],
+        b"URL\nhttps://example.com\nnot-a-url\nhttp://[::1\nhttps://example.org\n",
+    );
+    assert!(output.status.success());
+    let rows = records(&output.stdout);
+    assert_eq!(rows.len(), 2);
+    assert!(rows.iter().all(|row| row["error"].is_empty()));
+
+    let lines = std::fs::read_to_string(&invalid).expect("invalid output");
+    std::fs::remove_file(&invalid).expect("remove invalid output");
+    assert_eq!(lines, "not-a-url\nhttp://[::1\n");
+
+    let output = urlsplit_stdin(
+        &[
+            "--invalid-output",
+            invalid.to_str().unwrap(),
+            "--redact-query-values",
+        ],
+        b"URL\nhttp://[::1/?token=SECRET\n",
+    );
+    assert!(output.status.success());
+    let lines = std::fs::read_to_string(&invalid).expect("invalid output");
+    std::fs::remove_file(&invalid).expect("remove invalid output");
+    assert_eq!(lines, "http://[::1/?token=REDACTED\n");
+}
+
+#[test]
+fn test_passthrough_errors_to_stderr() {
+    let output = urlsplit_stdin(
+        &["--passthrough-errors-to-stderr"],
+        b"URL\nhttps://example.com\nnot-a-url\nhttps://example.org\nhttp://[::1\nmailto:a@example.com\n",
+    );
+    assert!(output.status.success());
+    let rows = records(&output.stdout);
+    assert_eq!(rows.len(), 3);
+    assert!(rows[..2].iter().all(|row| row["error"].is_empty()));
+    assert_eq!(rows[2]["url"], "mailto:a@example.com");
+    assert_eq!(rows[2]["error_kind"], "psl");
+
+    let stderr = String::from_utf8(output.stderr).expect("Valid utf-8 errors from urlsplit");
+    let lines = stderr.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        vec![
+            "line 3: not-a-url: relative URL without a base",
+            "line 5: http://[::1: invalid IPv6 address",
+        ]
+    );
+}
+
+#[test]
+fn test_rows_per_file() {
+    let dir = env::temp_dir().join(format!("urlsplit-chunks-{}", process::id()));
+    std::fs::create_dir_all(&dir).expect("create output directory");
+    let pattern = dir.join("out-{}.csv");
+    let output = urlsplit_stdin(
+        &[
+            "--rows-per-file",
+            "2",
+            "--output-pattern",
+            pattern.to_str().unwrap(),
+        ],
+        b"URL\nhttps://a.example\nhttps://b.example\nhttps://c.example\n",
+    );
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let chunk = |n: u64| {
+        let path = dir.join(format!("out-{}.csv", n));
+        std::fs::read(path).map(|data| records(&data))
+    };
+    let first = chunk(1).expect("first output file");
+    let second = chunk(2).expect("second output file");
+    assert!(chunk(3).is_err());
+    std::fs::remove_dir_all(&dir).expect("remove output directory");
+
+    assert_eq!(first.len(), 2);
+    assert_eq!(first[1]["url"], "https://b.example");
+    assert_eq!(second.len(), 1);
+    assert_eq!(second[0]["url"], "https://c.example");
+
+    let output = urlsplit_stdin(&["--rows-per-file", "2"], b"URL\n");
+    assert!(!output.status.success());
+    let output = urlsplit_stdin(
+        &["--rows-per-file", "2", "--output-pattern", "out.csv"],
+        b"URL\n",
+    );
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_crlf() {
+    let input = b"URL\nhttps://example.com/a\n";
+
+    let output = urlsplit_stdin(&["--crlf", "-d", ";", "-q"], input);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).expect("Valid utf-8 output from urlsplit");
+    let lines = stdout.split_inclusive('\n').collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines.iter().all(|line| line.ends_with("\r\n")));
+    assert!(lines[0].starts_with("url;scheme;netloc;"));
+    assert!(lines[1].starts_with("https://example.com/a;https;"));
+}
+
+#[test]
+fn test_input_terminator() {
+    let output = urlsplit_stdin(
+        &["--input-terminator", "\\0"],
+        b"URL\0https://example.com/a b\0https://example.org\0",
+    );
+    assert!(output.status.success());
+    let rows = records(&output.stdout);
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0]["path"], "/a%20b");
+    assert_eq!(rows[1]["hostname"], "example.org");
+}
+
+#[test]
+fn test_from_html() {
+    let output = urlsplit_stdin(
+        &["--from-html"],
+        b"<p>See <a href=\"https://example.com/a\">a</a>, <img src='http://cdn.example.org/b.png'></p>\n<p>none</p>\n<a href=https://example.net>c</a>\n",
+    );
+    assert!(output.status.success());
+    let rows = records(&output.stdout);
+    let urls = rows
+        .iter()
+        .map(|row| row["url"].as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        urls,
+        vec![
+            "https://example.com/a",
+            "http://cdn.example.org/b.png",
+            "https://example.net"
+        ]
+    );
+
+    // Quotes in the HTML aren't taken as CSV quoting, even with `-q`.
+    let output = urlsplit_stdin(
+        &["--from-html", "-q"],
+        b"<a href='https://example.com/a,\"b\"/c'>a</a>\n",
+    );
+    assert!(output.status.success());
+    let rows = records(&output.stdout);
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["url"], "https://example.com/a,\"b\"/c");
+    assert_eq!(rows[0]["path"], "/a,%22b%22/c");
+}
+
+#[test]
+fn test_from_access_log() {
+    let log = b"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] \"GET /a.html?b=1 HTTP/1.1\" 200 2326 \"-\" \"curl/7.1, like Gecko\"\n\
+        garbage\n\
+        ::1 - - [10/Oct/2000:13:55:37 -0700] \"POST /submit HTTP/1.0\" 302 -\n";
+    let output = urlsplit_stdin(&["--from-access-log", "--base", "https://example.com"], log);
+    assert!(output.status.success());
+    let rows = records(&output.stdout);
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[0]["url"], "https://example.com/a.html?b=1");
+    assert_eq!(rows[0]["query"], "b=1");
+    assert_eq!(rows[1]["url"], "garbage");
+    assert_eq!(rows[1]["error_kind"], "malformed-log-line");
+    assert_eq!(rows[2]["path"], "/submit");
+
+    let output = urlsplit_stdin(&["--from-access-log"], log);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_check_psl() {
+    let output = urlsplit_stdin(&["--check-psl"], b"URL\nhttps://example.com\n");
+    assert!(output.status.success());
+    assert_eq!(records(&output.stdout)[0]["suffix"], "com");
+}
+
+#[test]
+fn test_columns_from_file() {
+    let columns = env::temp_dir().join(format!("urlsplit-columns-{}.txt", process::id()));
+    std::fs::write(
+        &columns,
+        "# the site, then its path\nregistration = site\n\npath\n",
+    )
+    .unwrap();
+    let output = urlsplit_stdin(
+        &["--columns-from-file", columns.to_str().unwrap()],
+        b"URL\nhttps://www.example.com/a\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "site,path\nexample.com,/a\n"
+    );
+
+    // The schema and a dry run describe only the columns written.
+    let output = urlsplit_stdin(
+        &["--schema", "--columns-from-file", columns.to_str().unwrap()],
+        b"",
+    );
+    assert!(output.status.success());
+    let schema: Vec<serde_json::Value> =
+        serde_json::from_slice(&output.stdout).expect("Valid JSON schema from urlsplit");
+    let names = schema.iter().map(|c| c["name"].clone()).collect::<Vec<_>>();
+    assert_eq!(names, ["site", "path"]);
+    assert!(schema[0]["description"]
+        .as_str()
+        .unwrap()
+        .starts_with("The suffix and domain, combined"));
+    let output = urlsplit_stdin(
+        &[
+            "--dry-run",
+            "--columns-from-file",
+            columns.to_str().unwrap(),
+        ],
+        b"",
+    );
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("columns: site,path\n"));
+
+    std::fs::write(&columns, "hostname\nnot_a_column\n").unwrap();
+    let output = urlsplit_stdin(
+        &["--columns-from-file", columns.to_str().unwrap()],
+        b"URL\nhttps://www.example.com/a\n",
+    );
+    std::fs::remove_file(&columns).unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("no output column named 'not_a_column'"));
+}
+
+#[test]
+fn test_fields_regex() {
+    let output = urlsplit_stdin(
+        &["--fields-regex", "^host"],
+        b"URL\nhttps://www.example.com/a\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "hostname,host_punycode\nwww.example.com,false\n"
+    );
+
+    let output = urlsplit_stdin(&["--schema", "--fields-regex", "^host"], b"");
+    assert!(output.status.success());
+    let schema: Vec<serde_json::Value> =
+        serde_json::from_slice(&output.stdout).expect("Valid JSON schema from urlsplit");
+    let names = schema.iter().map(|c| c["name"].clone()).collect::<Vec<_>>();
+    assert_eq!(names, ["hostname", "host_punycode"]);
+
+    let output = urlsplit_stdin(&["--fields-regex", "^nothing$"], b"URL\n");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_encode_output() {
+    let output = urlsplit_stdin(
+        &["--encode-output", "-q"],
+        b"URL\n\"data:text/plain,50%25\"\n",
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let row = stdout.lines().nth(1).unwrap();
+    assert!(row.starts_with("data:text/plain%2C50%2525,data,"));
+
+    // Even with quoting, no field is quoted.
+    let output = urlsplit_stdin(
+        &["--encode-output", "-q"],
+        b"URL\n\"https://example.com/\"\"a\"\"\"\n",
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let row = stdout.lines().nth(1).unwrap();
+    assert!(
+        row.starts_with("https://example.com/%22a%22,https,"),
+        "{}",
+        row
+    );
+}
+
+#[test]
+fn test_psl_source() {
+    let list = env::temp_dir().join(format!("urlsplit-psl-source-{}.dat", process::id()));
+    std::fs::write(&list, "// A custom list\ninternal\ncorp.internal\n").unwrap();
+    let output = urlsplit_stdin(
+        &["--psl-source", list.to_str().unwrap()],
+        b"URL\nhttps://www.app.corp.internal/\n",
+    );
+    std::fs::remove_file(&list).unwrap();
+    assert!(output.status.success());
+    let rows = records(&output.stdout);
+    assert_eq!(rows[0]["suffix"], "corp.internal");
+    assert_eq!(rows[0]["registration"], "app.corp.internal");
+
+    let output = urlsplit_stdin(
+        &["--psl-source", "/nonexistent/public_suffix_list.dat"],
+        b"URL\nhttps://example.com/\n",
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("can't load the public suffix list"));
+}
+
+#[test]
+fn test_psl_source_url() {
+    // Serve the list once, over plain HTTP, from a local server.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/list.dat", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0; 1024];
+        let _ = std::io::Read::read(&mut stream, &mut request);
+        let list = "internal\ncorp.internal\n";
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            list.len(),
+            list
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+    });
+
+    let output = urlsplit_stdin(
+        &["--psl-source", &url, "--check-psl"],
+        b"URL\nhttps://www.app.corp.internal/\n",
+    );
+    server.join().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("failed its check"));
+    assert!(stderr.contains(&format!("Check the list at {}.", url)));
+    assert!(!stderr.contains(".tld_cache"));
+}
+
+#[test]
+fn test_duplicate_columns() {
+    let output = urlsplit_stdin(
+        &["--url-columns", "a,a"],
+        b"a,b\nhttps://example.com,https://example.org\n",
+    );
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("more than one output column is named 'a_url'"));
+}
+
+#[test]
+fn test_jobs_without_psl_cache() {
+    // The cache is found relative to the working directory, so this starts
+    // without one.
+    let dir = env::temp_dir().join(format!("urlsplit-fresh-cache-{}", process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut input = b"URL\n".to_vec();
+    for i in 0..200 {
+        writeln!(input, "https://host{}.example.co.uk/", i).unwrap();
+    }
+
+    let mut child = process::Command::new(get_workdir().join("urlsplit"))
+        .args(["--jobs", "8", "--psl-timeout", "60"])
+        .current_dir(&dir)
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .expect("Failed to execute urlsplit");
+    child.stdin.take().unwrap().write_all(&input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    let leftover = std::fs::read_dir(&dir).unwrap().count();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(output.status.success());
+    let rows = records(&output.stdout);
+    assert_eq!(rows.len(), 200);
+    for (i, row) in rows.iter().enumerate() {
+        assert_eq!(row["url"], format!("https://host{}.example.co.uk/", i));
+        assert_eq!(row["registration"], "example.co.uk");
+    }
+    assert_eq!(leftover, 0);
+}
+
+#[test]
+fn test_trim_trailing_empty() {
+    let output = urlsplit_stdin(
+        &["--trim-trailing-empty", "--no-error-column"],
+        b"URL\nhttps://example.com/\n",
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert!(lines[0].ends_with(",special_use"));
+    assert!(lines[1].ends_with(",example,,com,example.com"));
+
+    let output = urlsplit_stdin(
+        &["--trim-trailing-empty", "--format", "json"],
+        b"URL\nhttps://example.com/\n",
+    );
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_compare_columns() {
+    let output = urlsplit_stdin(
+        &[
+            "--url-columns",
+            "a,b",
+            "--compare-columns",
+            "a_registration,b_registration",
+        ],
+        b"a,b\nhttps://www.example.com,http://example.com/x\nhttps://example.com,https://example.org\n",
+    );
+    assert!(output.status.success());
+    let rows = records(&output.stdout);
+    assert_eq!(rows[0]["equal"], "true");
+    assert_eq!(rows[1]["equal"], "false");
+
+    // The comparison comes before the timing, in the header and the rows.
+    let output = urlsplit_stdin(
+        &[
+            "--compare-columns",
+            "scheme,scheme",
+            "--debug-timing",
+            "--jobs",
+            "2",
+        ],
+        b"URL\nhttps://example.com\nnot-a-url\n",
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout
+        .lines()
+        .next()
+        .unwrap()
+        .ends_with(",equal,parse_micros"));
+    let rows = records(stdout.as_bytes());
+    assert_eq!(rows.len(), 2);
+    assert!(rows.iter().all(|row| row["equal"] == "true"));
+    assert!(rows
+        .iter()
+        .all(|row| row["parse_micros"].parse::<u64>().is_ok()));
+
+    let output = urlsplit_stdin(
+        &["--compare-columns", "registration,nope"],
+        b"URL\nhttps://example.com\n",
+    );
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_empty_input() {
+    let output = urlsplit_stdin(&[], b"");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 1);
+    assert!(stdout.starts_with("url,scheme,"));
+
+    let output = urlsplit_stdin(&["--fail-on-empty"], b"");
+    assert!(!output.status.success());
+    assert_eq!(records(&output.stdout).len(), 0);
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("the input had no URLs"));
+
+    let output = urlsplit_stdin(&["--fail-on-empty"], b"URL\nhttps://example.com\n");
+    assert!(output.status.success());
+
+    // Records without any links have no URLs to split.
+    let output = urlsplit_stdin(&["--from-html", "--fail-on-empty"], b"<p>none</p>\n");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_assert_suffix() {
+    let output = urlsplit_stdin(
+        &["--assert-suffix", ".gov"],
+        b"URL\nhttps://www.usa.gov\nhttps://nasa.gov/a\n",
+    );
+    assert!(output.status.success());
+
+    let output = urlsplit_stdin(
+        &["--assert-suffix", "gov"],
+        b"URL\nhttps://www.usa.gov\nhttps://example.com/a\nhttps://example.org\n",
+    );
+    assert!(!output.status.success());
+    assert_eq!(records(&output.stdout).len(), 3);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error parsing URLs: the suffix of 'https://example.com/a' isn't 'gov'\n"
+    );
+}
+
+#[test]
+fn test_validate_only() {
+    let output = urlsplit_stdin(&["--validate-only"], b"URL\nhttps://example.com\n");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"");
+
+    let output = urlsplit_stdin(
+        &["--validate-only"],
+        b"URL\nhttps://example.com\nnot-a-url\n",
+    );
+    assert!(!output.status.success());
+    assert_eq!(output.stdout, b"");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error parsing URLs: 1 of 2 URLs failed to parse\n"
+    );
+}
+
+#[test]
+fn test_buffer_size() {
+    let input = b"URL\nhttps://a.example.com/some/long/path\nhttps://b.example.com\n";
+
+    let output = urlsplit_stdin(&["--buffer-size", "1"], input);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, urlsplit_stdin(&[], input).stdout);
+
+    let output = urlsplit_stdin(&["--buffer-size", "0"], input);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_dry_run() {
+    let output = urlsplit_stdin(
+        &["--dry-run", "--no-error-column", "--url-columns", "a,b"],
+        b"not,even,read\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"");
+
+    let stderr = String::from_utf8(output.stderr).expect("Valid utf-8 output from urlsplit");
+    let columns = stderr.lines().next().expect("columns line");
+    assert!(columns.starts_with("columns: a_url,a_scheme,"));
+    assert!(columns.contains(",b_hostname,"));
+    assert!(!columns.contains("error"));
+    assert!(stderr.contains("delimiter: ','\n"));
+}
+
+#[test]
+fn test_explain() {
+    let output = urlsplit_stdin(
+        &["--explain", "https://www.example.co.uk:8080/a/../b?x=1"],
+        b"",
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).expect("Valid utf-8 output from urlsplit");
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "url: https://www.example.co.uk:8080/a/../b?x=1");
+    assert!(lines.contains(&"hostname: www.example.co.uk, a registered name"));
+    assert!(lines.contains(&"port: 8080, as written"));
+    assert!(lines.contains(&"path: /b"));
+    assert!(lines.contains(&"suffix: co.uk, matching the rule `co.uk` of the public suffix list"));
+    assert!(lines.contains(&"subdomain: www, the labels before the domain"));
+
+    let explain = |url| {
+        let output = urlsplit_stdin(&["--explain", url], b"");
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).expect("Valid utf-8 output from urlsplit")
+    };
+    let stdout = explain("https://a.b.ck/");
+    assert!(
+        stdout
+            .contains("suffix: b.ck, matching the wildcard rule `*.ck` of the public suffix list"),
+        "{}",
+        stdout
+    );
+    let stdout = explain("https://www.city.kawasaki.jp/");
+    assert!(
+        stdout.contains("the exception rule `!city.kawasaki.jp`"),
+        "{}",
+        stdout
+    );
+
+    // A URL which fails a check is still described.
+    let output = urlsplit_stdin(
+        &[
+            "--explain",
+            "http://www.example.com/",
+            "--require-tls",
+            "example.com",
+        ],
+        b"",
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).expect("Valid utf-8 output from urlsplit");
+    assert!(stdout.contains("(insecure-scheme)\nThe URL was parsed, but failed a check."));
+    assert!(stdout.contains("\nhostname: www.example.com, a registered name\n"));
+
+    let output = urlsplit_stdin(&["--explain", "not-a-url"], b"");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).expect("Valid utf-8 output from urlsplit");
+    assert_eq!(
+        stdout,
+        "url: not-a-url\nerror: relative URL without a base (relative-url)\n\
+         The URL couldn't be parsed, so it has no other parts.\n"
+    );
+}
+
+#[test]
+fn test_schema() {
+    let output = urlsplit_stdin(
+        &["--schema", "--url-columns", "a", "--split-ip-octets"],
+        b"",
+    );
+    assert!(output.status.success());
+    let schema: Vec<serde_json::Value> =
+        serde_json::from_slice(&output.stdout).expect("Valid JSON schema from urlsplit");
+    let column = |name: &str| {
+        schema
+            .iter()
+            .find(|c| c["name"] == name)
+            .unwrap_or_else(|| panic!("no {} column", name))
+            .clone()
+    };
+    assert_eq!(column("a_url")["type"], "string");
+    assert_eq!(column("a_url")["description"], "The full input URL.");
+    assert_eq!(column("a_port")["type"], "int");
+    assert_eq!(column("a_host_punycode")["type"], "bool");
+    assert!(column("a_origin")["description"]
+        .as_str()
+        .unwrap()
+        .ends_with("like `data`, give `null`."));
+    assert_eq!(column("a_octet1")["type"], "int");
+    assert!(schema
+        .iter()
+        .all(|c| !c["description"].as_str().unwrap().is_empty()));
+}
+
+#[test]
+fn test_continuation() {
+    let input = b"URL\nhttps://example.com/a/very/long/\\\npath?query=1\nhttps://example.org\n";
+
+    let output = urlsplit_stdin(&["--continuation"], input);
+    assert!(output.status.success());
+    let rows = records(&output.stdout);
+    assert_eq!(rows.len(), 2);
+    assert_eq!(
+        rows[0]["url"],
+        "https://example.com/a/very/long/path?query=1"
+    );
+    assert_eq!(rows[0]["path"], "/a/very/long/path");
+    assert_eq!(rows[1]["hostname"], "example.org");
+
+    let output = urlsplit_stdin(&[], input);
+    assert_eq!(records(&output.stdout).len(), 3);
+}
+
+#[test]
+fn test_json_format() {
+    let input = b"URL\nhttps://example.com/a\nnot-a-url\n";
+
+    let output = urlsplit_stdin(&["--format", "json"], input);
+    assert!(output.status.success());
+    assert_eq!(output.stdout.iter().filter(|&&b| b == b'\n').count(), 1);
+    let rows: Vec<HashMap<String, String>> =
+        serde_json::from_slice(&output.stdout).expect("Valid JSON output from urlsplit");
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0]["path"], "/a");
+    assert_eq!(rows[1]["error_kind"], "relative-url");
+
+    let pretty = urlsplit_stdin(&["--format", "json", "--pretty-json"], input);
+    assert!(pretty.status.success());
+    assert!(pretty.stdout.starts_with(b"[\n  {\n    \"url\": "));
+    let pretty_rows: Vec<HashMap<String, String>> =
+        serde_json::from_slice(&pretty.stdout).expect("Valid JSON output from urlsplit");
+    assert_eq!(pretty_rows, rows);
+
+    let output = urlsplit_stdin(&["--pretty-json"], input);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_tsv_format() {
+    let input = b"URL\n\"https://example.com/a,b\"\n";
+
+    let output = urlsplit_stdin(&["--format", "tsv", "-q"], input);
+    assert!(output.status.success());
+    let rows = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .from_reader(&*output.stdout)
+        .deserialize()
+        .collect::<Result<Vec<HashMap<String, String>>, _>>()
+        .expect("Valid TSV output from urlsplit");
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["path"], "/a,b");
+
+    // The delimiter is still used to read the input.
+    let output = urlsplit_stdin(
+        &["--format", "tsv", "-d", ";"],
+        b"id;URL\n1;https://example.com/a,b\n",
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("url\tscheme\t"), "{}", stdout);
+    assert!(stdout.contains("\t/a,b\t"), "{}", stdout);
+}
+
+#[test]
+fn test_table_format() {
+    let input = b"URL\nhttps://example.com/a\nhttps://example.org/a/much/longer/path\n";
+
+    let output = urlsplit_stdin(&["--format", "table", "--max-column-width", "12"], input);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).expect("Valid utf-8 from urlsplit");
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("url           scheme  "), "{}", stdout);
+    assert!(lines[1].starts_with("------------  ------  "), "{}", stdout);
+    assert!(lines[3].starts_with("https://exa…  https   "), "{}", stdout);
+
+    let output = urlsplit_stdin(&["--max-column-width", "12"], input);
+    assert!(!output.status.success());
+
+    let output = urlsplit_stdin(
+        &["--format", "table", "--no-headers", "--color", "always"],
+        b"https://example.com/a\nnope\n",
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).expect("Valid utf-8 from urlsplit");
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("https://example.com/a "), "{}", stdout);
+    assert!(lines[1].starts_with("\x1b[31mnope "), "{}", stdout);
+
+    let output = urlsplit_stdin(
+        &["--format", "table", "--color", "never"],
+        b"URL
+nope
+",
+    );
+    assert!(output.status.success());
+    assert!(!output.stdout.contains(&b'\x1b'));
+
+    let output = urlsplit_stdin(&["--color", "always"], input);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_template() {
+    let input = b"URL\nhttps://www.example.com/a\nhttps://example.org/b?q\n";
+
+    let output = urlsplit_stdin(&["--template", "{registration}\\t{path}"], input);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"example.com\t/a\nexample.org\t/b\n");
+
+    let output = urlsplit_stdin(&["--template", "{host}"], input);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).expect("Valid utf-8 error from urlsplit");
+    assert!(
+        stderr.contains("no output column named 'host'"),
+        "{}",
+        stderr
+    );
+
+    let output = urlsplit_stdin(&["--template", "{path}", "--format", "json"], input);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_jsonl_format() {
+    let input = b"URL\nhttps://example.com:8080/a\nnot-a-url\n";
+
+    let output = urlsplit_stdin(&["--format", "jsonl", "--json-types"], input);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).expect("Valid utf-8 from urlsplit");
+    let rows = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Valid JSON line from urlsplit"))
+        .collect::<Vec<serde_json::Value>>();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0]["port"], 8080);
+    assert_eq!(rows[1]["error_kind"], "relative-url");
+
+    // The keys are the columns of the CSV header, in the same order.
+    let csv = urlsplit_stdin(&[], input);
+    let header = String::from_utf8(csv.stdout).expect("Valid utf-8 from urlsplit");
+    let header = header
+        .lines()
+        .next()
+        .unwrap()
+        .split(',')
+        .collect::<Vec<_>>();
+    let keys = stdout.lines().next().unwrap();
+    let keys = header
+        .iter()
+        .map(|name| keys.find(&format!("\"{}\":", name)).expect(name))
+        .collect::<Vec<_>>();
+    assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+
+    let output = urlsplit_stdin(&["--format", "jsonl", "--pretty-json"], input);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_json_types() {
+    let input = b"URL\nhttps://xn--caf-dma.example.com:8080/a\n";
+
+    let output = urlsplit_stdin(&["--format", "json", "--json-types"], input);
+    assert!(output.status.success());
+    let rows: Vec<serde_json::Value> =
+        serde_json::from_slice(&output.stdout).expect("Valid JSON output from urlsplit");
+    assert_eq!(rows[0]["port"], 8080);
+    assert_eq!(rows[0]["host_punycode"], true);
+    assert_eq!(rows[0]["query"], serde_json::Value::Null);
+    assert_eq!(rows[0]["username"], "");
+
+    let output = urlsplit_stdin(&["--format", "json"], input);
+    let rows: Vec<serde_json::Value> =
+        serde_json::from_slice(&output.stdout).expect("Valid JSON output from urlsplit");
+    assert_eq!(rows[0]["port"], "8080");
+
+    let output = urlsplit_stdin(&["--json-types"], input);
+    assert!(!output.status.success());
+}
+
+// Parse urlsplit's CSV output into rows keyed by column name.
+fn records(stdout: &[u8]) -> Vec<HashMap<String, String>> {
+    csv::Reader::from_reader(stdout)
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .expect("Valid CSV output from urlsplit")
+}
+
+#[cfg(unix)]
+#[test]
+fn test_interrupt() {
+    let mut child = process::Command::new(get_workdir().join("urlsplit"))
+        .arg("--passthrough-errors-to-stderr")
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .expect("Failed to execute urlsplit");
+    let mut stdin = child.stdin.take().expect("stdin for urlsplit");
+    stdin
+        .write_all(b"URL\nhttps://a.example\nnot-a-url\n")
+        .expect("Write input to urlsplit");
+
+    // The error for the last record shows that it was split, and that
+    // urlsplit is waiting for more input.
+    let mut stderr = std::io::BufReader::new(child.stderr.take().expect("stderr for urlsplit"));
+    let mut line = String::new();
+    std::io::BufRead::read_line(&mut stderr, &mut line).expect("Read errors from urlsplit");
+    assert_eq!(line, "line 3: not-a-url: relative URL without a base\n");
+
+    let status = process::Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .expect("Failed to interrupt urlsplit");
+    assert!(status.success());
+
+    // Input stays open until urlsplit exits, so that it's the interrupt, and
+    // not the end of the input, which stops it.
+    let output = child
+        .wait_with_output()
+        .expect("Failed to wait on urlsplit");
+    drop(stdin);
+
+    assert_eq!(output.status.code(), Some(130));
+    line.clear();
+    std::io::Read::read_to_string(&mut stderr, &mut line).expect("Read errors from urlsplit");
+    assert_eq!(line, "interrupted after processing 2 records\n");
+    let urls = records(&output.stdout)
+        .iter()
+        .map(|row| row["url"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(urls, vec!["https://a.example"]);
+}
+
+fn urlsplit_stdin(args: &[&str], input: &[u8]) -> process::Output {
+    let mut child = process::Command::new(get_workdir().join("urlsplit"))
+        .args(args)
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .expect("Failed to execute urlsplit");
+    child
+        .stdin
+        .take()
+        .expect("stdin for urlsplit")
+        .write_all(input)
+        .expect("Write input to urlsplit");
+    child
+        .wait_with_output()
+        .expect("Failed to wait on urlsplit")
+}
+
 fn get_workdir() -> PathBuf {
     let mut root = env::current_exe()
         .unwrap()